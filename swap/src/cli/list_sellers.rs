@@ -1,33 +1,64 @@
 use crate::network::quote::BidQuote;
-use crate::network::{quote, swarm};
+use crate::network::spot_price::{SpotPriceRequest, SpotPriceResponse};
+use crate::network::{quote, spot_price, swarm};
 use crate::rendezvous::XmrBtcNamespace;
 use anyhow::Result;
-use futures::StreamExt;
+use futures::{Stream, StreamExt};
 use libp2p::multiaddr::Protocol;
-use libp2p::ping::{Ping, PingConfig, PingEvent};
-use libp2p::rendezvous::{Namespace, Rendezvous};
-use libp2p::request_response::{RequestResponseEvent, RequestResponseMessage};
+use libp2p::ping::{Ping, PingConfig, PingEvent, PingSuccess};
+use libp2p::rendezvous::{Cookie, Namespace, Rendezvous};
+use libp2p::request_response::{
+    InboundFailure, OutboundFailure, RequestResponseEvent, RequestResponseMessage,
+};
 use libp2p::swarm::SwarmEvent;
 use libp2p::{identity, rendezvous, Multiaddr, PeerId, Swarm};
 use serde::Serialize;
-use serde_with::{serde_as, DisplayFromStr};
-use std::collections::HashMap;
-use std::time::Duration;
+use serde_with::{serde_as, DisplayFromStr, DurationSeconds};
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use tokio::time::{Interval, Sleep};
 
+/// How long we wait for an individual peer to answer a quote request before
+/// treating it as a timeout, even if the overall discovery deadline has not
+/// yet passed. Goes through the same retry-once-then-give-up path as a
+/// request-response-level timeout (see [`EventLoop::quote_failure`]), and is
+/// far shorter than `request_response::TIMEOUT` so that path, not the
+/// hour-long protocol timeout, is what actually governs a stale seller.
+const QUOTE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Ping interval used while discovery is in progress. Short enough that we
+/// get at least one RTT sample per seller before the quote timeout elapses,
+/// unlike the 24h interval the `Ping` behaviour defaults to, which would
+/// never complete within a single discovery run.
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often we re-issue `discover` against the rendezvous point, using the
+/// cookie from the previous round so only newly-registered makers come back.
+const REDISCOVER_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Discover sellers and return everyone quoted (or given up on), stopping as
+/// soon as every currently-known seller has an outcome rather than always
+/// waiting the full `timeout`. A thin, collecting wrapper around
+/// [`EventLoop::watch`] for callers that just want a single snapshot rather
+/// than a live feed.
 pub async fn list_sellers(
     rendezvous_node_peer_id: PeerId,
     rendezvous_node_addr: Multiaddr,
     namespace: XmrBtcNamespace,
     tor_socks5_port: u16,
     identity: identity::Keypair,
-) -> Result<Vec<Seller>> {
+    timeout: Duration,
+    requested_amount: Option<bitcoin::Amount>,
+) -> Result<Vec<SellerStatus>> {
     let behaviour = Behaviour {
         rendezvous: Rendezvous::new(identity.clone(), rendezvous::Config::default()),
         quote: quote::cli(),
+        spot_price: spot_price::cli(),
         ping: Ping::new(
             PingConfig::new()
                 .with_keep_alive(false)
-                .with_interval(Duration::from_secs(86_400)),
+                .with_interval(PING_INTERVAL),
         ),
     };
     let mut swarm = swarm::cli(identity, tor_socks5_port, behaviour).await?;
@@ -39,8 +70,20 @@ pub async fn list_sellers(
         rendezvous_node_peer_id,
         rendezvous_node_addr,
         namespace,
+        timeout,
+        requested_amount,
+        true,
     );
-    let sellers = event_loop.run().await;
+    let mut sellers: Vec<SellerStatus> = event_loop.watch().collect().await;
+
+    // Most reachable makers first; sellers without a measured latency (or
+    // unreachable ones) sort last. A live `watch()` consumer sees arrival
+    // order instead, since a single snapshot sort doesn't make sense for an
+    // open-ended stream.
+    sellers.sort_by_key(|status| match status {
+        SellerStatus::Online(seller) => seller.latency.unwrap_or(Duration::MAX),
+        SellerStatus::Unreachable(_) => Duration::MAX,
+    });
 
     Ok(sellers)
 }
@@ -52,12 +95,96 @@ pub struct Seller {
     pub peer_id: PeerId,
     pub multiaddr: Multiaddr,
     pub quote: BidQuote,
+    /// The exact amount of XMR this seller committed to sending for the
+    /// requested BTC amount, if one was requested and the seller agreed to
+    /// quote it.
+    pub xmr: Option<monero::Amount>,
+    /// Round-trip time measured by the `Ping` behaviour while this seller
+    /// was being quoted, used to rank the most reachable makers first.
+    #[serde_as(as = "Option<DurationSeconds<f64>>")]
+    pub latency: Option<Duration>,
+}
+
+/// A peer that was discovered via the rendezvous point but was given up on
+/// before it answered our quote request, for the [`UnreachableReason`] given.
+/// Peers still waiting for a quote when the overall discovery deadline
+/// elapses are not reported.
+#[serde_as]
+#[derive(Debug, Serialize)]
+pub struct UnreachableSeller {
+    #[serde_as(as = "DisplayFromStr")]
+    pub peer_id: PeerId,
+    pub reason: UnreachableReason,
+}
+
+/// Why we gave up on a discovered peer, so the CLI can tell apart a maker
+/// that is merely running an older/incompatible version from one that is
+/// genuinely unreachable, e.g. "2 makers skipped: unsupported protocol
+/// version".
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnreachableReason {
+    /// The peer does not speak a version of the protocol we understand.
+    UnsupportedProtocolVersion,
+    /// We could not establish, or lost, a connection to the peer.
+    ConnectionFailed,
+    /// The peer did not respond in time, even after a retry.
+    Timeout,
+}
+
+/// What to do about a failed request, as classified by
+/// [`classify_outbound_failure`] / [`classify_inbound_failure`].
+enum FailureAction {
+    /// Benign and possibly transient: give the peer one more chance.
+    Retry,
+    /// Unrecoverable: stop tracking the peer and report why.
+    GiveUp(UnreachableReason),
+}
+
+/// Classifies an outbound request-response failure: a peer speaking an
+/// unsupported protocol version, or one we simply couldn't connect to, is
+/// reported distinctly from a timeout, which is retried once before we give
+/// up on the peer.
+fn classify_outbound_failure(error: &OutboundFailure) -> FailureAction {
+    match error {
+        OutboundFailure::UnsupportedProtocols => {
+            FailureAction::GiveUp(UnreachableReason::UnsupportedProtocolVersion)
+        }
+        OutboundFailure::ConnectionClosed | OutboundFailure::DialFailure => {
+            FailureAction::GiveUp(UnreachableReason::ConnectionFailed)
+        }
+        OutboundFailure::Timeout => FailureAction::Retry,
+    }
+}
+
+/// See [`classify_outbound_failure`].
+fn classify_inbound_failure(error: &InboundFailure) -> FailureAction {
+    match error {
+        InboundFailure::UnsupportedProtocols => {
+            FailureAction::GiveUp(UnreachableReason::UnsupportedProtocolVersion)
+        }
+        InboundFailure::ConnectionClosed | InboundFailure::ResponseOmission => {
+            FailureAction::GiveUp(UnreachableReason::ConnectionFailed)
+        }
+        InboundFailure::Timeout => FailureAction::Retry,
+    }
+}
+
+/// The outcome of quoting a single discovered peer, distinguishing sellers
+/// that responded in time from ones that did not so the caller can report
+/// e.g. "3 of 5 sellers responded".
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum SellerStatus {
+    Online(Seller),
+    Unreachable(UnreachableSeller),
 }
 
 #[derive(Debug)]
 pub enum OutEvent {
     Rendezvous(rendezvous::Event),
     Quote(quote::OutEvent),
+    SpotPrice(spot_price::OutEvent),
     Ping(PingEvent),
 }
 
@@ -73,18 +200,25 @@ impl From<quote::OutEvent> for OutEvent {
     }
 }
 
+impl From<spot_price::OutEvent> for OutEvent {
+    fn from(event: spot_price::OutEvent) -> Self {
+        OutEvent::SpotPrice(event)
+    }
+}
+
 #[derive(libp2p::NetworkBehaviour)]
 #[behaviour(event_process = false)]
 #[behaviour(out_event = "OutEvent")]
 pub struct Behaviour {
     pub rendezvous: Rendezvous,
     pub quote: quote::Behaviour,
+    pub spot_price: spot_price::Behaviour,
     pub ping: Ping,
 }
 
 #[derive(Debug)]
 enum QuoteStatus {
-    Pending,
+    Pending { since: Instant },
     Received(BidQuote),
 }
 
@@ -93,6 +227,21 @@ enum State {
     WaitForQuoteCompletion,
 }
 
+/// The result of driving the event loop for a single step, used to turn it
+/// into a [`Stream`] via [`EventLoop::watch`].
+enum Step {
+    /// A seller's outcome (quoted or given up on) is ready to be emitted.
+    Yield(SellerStatus),
+    /// Nothing to emit yet, keep driving the loop.
+    Continue,
+    /// Nothing left to drain; the stream ends here. Reached once the overall
+    /// discovery deadline elapsed (or the rendezvous point became
+    /// unreachable) *and* every seller we had a quote for has been flushed
+    /// via [`EventLoop::drain_unemitted`]; or, if `stop_when_idle` was set,
+    /// once every currently-known seller has an outcome.
+    Done,
+}
+
 pub struct EventLoop {
     swarm: Swarm<Behaviour>,
     rendezvous_peer_id: PeerId,
@@ -100,7 +249,44 @@ pub struct EventLoop {
     namespace: XmrBtcNamespace,
     asb_address: HashMap<PeerId, Multiaddr>,
     asb_quote_status: HashMap<PeerId, QuoteStatus>,
+    asb_spot_price: HashMap<PeerId, monero::Amount>,
+    /// Peers whose spot-price request has reached some terminal outcome
+    /// (an `Xmr`/`Error` response, or a protocol failure), whether or not
+    /// that outcome actually populated `asb_spot_price`. Lets us tell "still
+    /// waiting on a spot price" apart from "asked, and it came back empty".
+    spot_price_done: HashSet<PeerId>,
+    latencies: HashMap<PeerId, Duration>,
+    /// Peers we have observed at least one `PingEvent` for (success or
+    /// failure), whether or not it actually produced an RTT in `latencies`.
+    /// Bounds how long we wait for a latency sample to roughly one
+    /// `PING_INTERVAL`, rather than waiting indefinitely for a successful
+    /// ping that may never come (e.g. a peer that closes the connection).
+    ping_done: HashSet<PeerId>,
+    /// Peers already yielded as `SellerStatus::Online`, so a later event for
+    /// the same peer (e.g. a delayed spot-price response or ping) cannot
+    /// trigger a duplicate emission.
+    emitted: HashSet<PeerId>,
+    /// Peers whose quote request already timed out once; a second timeout
+    /// gives up on them instead of retrying again.
+    retried_quote: HashSet<PeerId>,
+    requested_amount: Option<bitcoin::Amount>,
+    /// Cookie from the most recent `Discovered` event, re-sent on every
+    /// `discover` call so the rendezvous point only returns new registrations.
+    cookie: Option<Cookie>,
+    rediscover_interval: Interval,
     state: State,
+    deadline: Pin<Box<Sleep>>,
+    /// Whether to end the stream once every currently-known seller has been
+    /// quoted (or given up on) instead of always running until `deadline`.
+    /// `list_sellers` wants this (a one-shot snapshot should return as soon
+    /// as it has its answer); a long-running `watch()` consumer that wants to
+    /// keep picking up newly-registered sellers does not.
+    stop_when_idle: bool,
+    /// Set once the deadline elapses or the rendezvous point becomes
+    /// unreachable: we stop driving the swarm and just flush whatever
+    /// sellers we already have a quote for but hadn't finished correlating
+    /// with a spot price / ping yet, instead of silently dropping them.
+    draining: bool,
 }
 
 impl EventLoop {
@@ -109,6 +295,9 @@ impl EventLoop {
         rendezvous_peer_id: PeerId,
         rendezvous_addr: Multiaddr,
         namespace: XmrBtcNamespace,
+        timeout: Duration,
+        requested_amount: Option<bitcoin::Amount>,
+        stop_when_idle: bool,
     ) -> Self {
         Self {
             swarm,
@@ -117,161 +306,469 @@ impl EventLoop {
             namespace,
             asb_address: Default::default(),
             asb_quote_status: Default::default(),
+            asb_spot_price: Default::default(),
+            spot_price_done: Default::default(),
+            latencies: Default::default(),
+            ping_done: Default::default(),
+            emitted: Default::default(),
+            retried_quote: Default::default(),
+            requested_amount,
+            cookie: None,
+            rediscover_interval: tokio::time::interval(REDISCOVER_INTERVAL),
             state: State::WaitForDiscovery,
+            deadline: Box::pin(tokio::time::sleep(timeout)),
+            stop_when_idle,
+            draining: false,
         }
     }
 
-    pub async fn run(mut self) -> Vec<Seller> {
-        loop {
-            tokio::select! {
-                swarm_event = self.swarm.select_next_some() => {
-                    match swarm_event {
-                        SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
-                            if peer_id == self.rendezvous_peer_id{
-                                tracing::info!(
-                                    "Connected to rendezvous point, discovering nodes in '{}' namespace ...",
-                                    self.namespace
-                                );
-
-                                self.swarm.behaviour_mut().rendezvous.discover(
-                                    Some(Namespace::new(self.namespace.to_string()).expect("our namespace to be a correct string")),
-                                    None,
-                                    None,
-                                    self.rendezvous_peer_id,
-                                );
-                            } else {
-                                let address = endpoint.get_remote_address();
-                                self.asb_address.insert(peer_id, address.clone());
-                            }
+    /// Discover sellers, yielding each one's outcome as soon as it is known.
+    /// Re-issues `discover` against the rendezvous point on
+    /// [`REDISCOVER_INTERVAL`] using the last-seen cookie, so makers that
+    /// register after the first batch are picked up without re-quoting peers
+    /// we already know about. Ends once the overall deadline elapses, or, if
+    /// `stop_when_idle` was set, as soon as every currently-known seller has
+    /// an outcome.
+    pub fn watch(self) -> impl Stream<Item = SellerStatus> {
+        futures::stream::unfold(self, |mut event_loop| async move {
+            loop {
+                match event_loop.step().await {
+                    Step::Yield(status) => return Some((status, event_loop)),
+                    Step::Continue => continue,
+                    Step::Done => return None,
+                }
+            }
+        })
+    }
+
+    async fn step(&mut self) -> Step {
+        if self.draining {
+            return self.drain_unemitted().unwrap_or(Step::Done);
+        }
+
+        tokio::select! {
+            swarm_event = self.swarm.select_next_some() => {
+                match swarm_event {
+                    SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                        if peer_id == self.rendezvous_peer_id{
+                            tracing::info!(
+                                "Connected to rendezvous point, discovering nodes in '{}' namespace ...",
+                                self.namespace
+                            );
+
+                            self.discover();
+                        } else {
+                            let address = endpoint.get_remote_address();
+                            self.asb_address.insert(peer_id, address.clone());
                         }
-                        SwarmEvent::UnreachableAddr { peer_id, error, address, .. } => {
-                            if address == self.rendezvous_addr {
-                                tracing::error!(
-                                    "Failed to connect to rendezvous point at {}: {}",
-                                    address,
-                                    error
-                                );
-
-                                // if the rendezvous node is unreachable we just stop
-                                return Vec::new();
-                            } else {
-                                tracing::debug!(
-                                    "Failed to connect to peer at {}: {}",
-                                    address,
-                                    error
-                                );
-
-                                // if a different peer than the rendezvous node is unreachable (i.e. a seller) we remove that seller from the quote status state
-                                self.asb_quote_status.remove(&peer_id);
+                    }
+                    SwarmEvent::UnreachableAddr { peer_id, error, address, .. } => {
+                        if address == self.rendezvous_addr {
+                            tracing::error!(
+                                "Failed to connect to rendezvous point at {}: {}",
+                                address,
+                                error
+                            );
+
+                            // if the rendezvous node is unreachable we stop discovering, but
+                            // still flush any seller we already have a quote for
+                            self.draining = true;
+                        } else {
+                            tracing::debug!(
+                                "Failed to connect to peer at {}: {}",
+                                address,
+                                error
+                            );
+
+                            // if a different peer than the rendezvous node is unreachable (i.e. a seller) we remove that seller from the quote status state
+                            if self.asb_quote_status.remove(&peer_id).is_some() {
+                                return Step::Yield(SellerStatus::Unreachable(UnreachableSeller {
+                                    peer_id,
+                                    reason: UnreachableReason::ConnectionFailed,
+                                }));
                             }
                         }
-                        SwarmEvent::Behaviour(OutEvent::Rendezvous(
-                                                  rendezvous::Event::Discovered { registrations, .. },
-                                              )) => {
-                            self.state = State::WaitForQuoteCompletion;
-
-                            for registration in registrations {
-                                let peer = registration.record.peer_id();
-                                for address in registration.record.addresses() {
-                                    tracing::info!("Discovered peer {} at {}", peer, address);
-
-                                    let p2p_suffix = Protocol::P2p(*peer.as_ref());
-                                    let _address_with_p2p = if !address
-                                        .ends_with(&Multiaddr::empty().with(p2p_suffix.clone()))
-                                    {
-                                        address.clone().with(p2p_suffix)
-                                    } else {
-                                        address.clone()
-                                    };
-
-                                    self.asb_quote_status.insert(peer, QuoteStatus::Pending);
-
-                                    // add all external addresses of that peer to the quote behaviour
-                                    self.swarm.behaviour_mut().quote.add_address(&peer, address.clone());
-                                }
+                    }
+                    SwarmEvent::Behaviour(OutEvent::Rendezvous(
+                                              rendezvous::Event::Discovered { registrations, cookie, .. },
+                                          )) => {
+                        self.state = State::WaitForQuoteCompletion;
+                        self.cookie = Some(cookie);
+
+                        for registration in registrations {
+                            let peer = registration.record.peer_id();
+
+                            // we already quoted (or are quoting) this peer in an earlier round, no
+                            // need to request another quote
+                            let already_known = self.asb_quote_status.contains_key(&peer);
+
+                            for address in registration.record.addresses() {
+                                tracing::info!("Discovered peer {} at {}", peer, address);
+
+                                let p2p_suffix = Protocol::P2p(*peer.as_ref());
+                                let _address_with_p2p = if !address
+                                    .ends_with(&Multiaddr::empty().with(p2p_suffix.clone()))
+                                {
+                                    address.clone().with(p2p_suffix)
+                                } else {
+                                    address.clone()
+                                };
+
+                                // add all external addresses of that peer to the quote behaviour
+                                self.swarm.behaviour_mut().quote.add_address(&peer, address.clone());
+                            }
+
+                            if !already_known {
+                                self.asb_quote_status.insert(peer, QuoteStatus::Pending { since: Instant::now() });
 
                                 // request the quote, if we are not connected to the peer it will be dialed automatically
                                 let _request_id = self.swarm.behaviour_mut().quote.send_request(&peer, ());
+
+                                if let Some(btc) = self.requested_amount {
+                                    let _request_id = self
+                                        .swarm
+                                        .behaviour_mut()
+                                        .spot_price
+                                        .send_request(&peer, SpotPriceRequest { btc });
+                                }
                             }
                         }
-                        SwarmEvent::Behaviour(OutEvent::Quote(quote_response)) => {
-                            match quote_response {
-                                RequestResponseEvent::Message { peer, message } => {
-                                    match message {
-                                        RequestResponseMessage::Response { response, .. } => {
-                                            if self.asb_quote_status.insert(peer, QuoteStatus::Received(response)).is_some() {
-                                                tracing::debug!(%peer, "Received bid quote {:?} from peer {}", response, peer);
-                                            } else {
-                                                tracing::error!(%peer, "Received bid quote from unexpected peer, this record will be removed!");
-                                                self.asb_quote_status.remove(&peer);
+                    }
+                    SwarmEvent::Behaviour(OutEvent::Quote(quote_response)) => {
+                        match quote_response {
+                            RequestResponseEvent::Message { peer, message } => {
+                                match message {
+                                    RequestResponseMessage::Response { response, .. } => {
+                                        if self.asb_quote_status.insert(peer, QuoteStatus::Received(response)).is_some() {
+                                            tracing::debug!(%peer, "Received bid quote {:?} from peer {}", response, peer);
+                                            if let Some(step) = self.try_emit_seller(peer) {
+                                                return step;
                                             }
+                                        } else {
+                                            tracing::error!(%peer, "Received bid quote from unexpected peer, this record will be removed!");
+                                            self.asb_quote_status.remove(&peer);
                                         }
-                                        RequestResponseMessage::Request { .. } => unreachable!()
                                     }
+                                    RequestResponseMessage::Request { .. } => unreachable!()
                                 }
-                                RequestResponseEvent::OutboundFailure { peer, error, .. } => {
-                                    if peer == self.rendezvous_peer_id {
-                                        tracing::debug!(%peer, "Outbound failure when communicating with rendezvous node: {:#}", error);
-                                    } else {
-                                        tracing::debug!(%peer, "Ignoring seller, because unable to request quote: {:#}", error);
-                                        self.asb_quote_status.remove(&peer);
-                                    }
+                            }
+                            RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+                                if peer == self.rendezvous_peer_id {
+                                    tracing::debug!(%peer, "Outbound failure when communicating with rendezvous node: {:#}", error);
+                                } else if let Some(status) = self.quote_failure(peer, classify_outbound_failure(&error)) {
+                                    return status;
+                                }
+                            }
+                            RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                                if peer == self.rendezvous_peer_id {
+                                    tracing::debug!(%peer, "Inbound failure when communicating with rendezvous node: {:#}", error);
+                                } else if let Some(status) = self.quote_failure(peer, classify_inbound_failure(&error)) {
+                                    return status;
                                 }
-                                RequestResponseEvent::InboundFailure { peer, error, .. } => {
-                                    if peer == self.rendezvous_peer_id {
-                                        tracing::debug!(%peer, "Inbound failure when communicating with rendezvous node: {:#}", error);
-                                    } else {
-                                        tracing::debug!(%peer, "Ignoring seller, because unable to request quote: {:#}", error);
-                                        self.asb_quote_status.remove(&peer);
+                            },
+                            RequestResponseEvent::ResponseSent { .. } => unreachable!()
+                        }
+                    }
+                    SwarmEvent::Behaviour(OutEvent::SpotPrice(spot_price_response)) => {
+                        match spot_price_response {
+                            RequestResponseEvent::Message { peer, message } => {
+                                match message {
+                                    RequestResponseMessage::Response { response, .. } => {
+                                        match response {
+                                            SpotPriceResponse::Xmr(xmr) => {
+                                                self.asb_spot_price.insert(peer, xmr);
+                                            }
+                                            SpotPriceResponse::Error(error) => {
+                                                tracing::debug!(%peer, "Seller refused to quote an exact amount: {:#}", error);
+                                            }
+                                        }
+                                        self.spot_price_done.insert(peer);
+                                        if let Some(step) = self.try_emit_seller(peer) {
+                                            return step;
+                                        }
                                     }
-                                },
-                                RequestResponseEvent::ResponseSent { .. } => unreachable!()
+                                    RequestResponseMessage::Request { .. } => unreachable!()
+                                }
+                            }
+                            RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+                                tracing::debug!(%peer, "Outbound failure when requesting spot price: {:#}", error);
+                                self.spot_price_done.insert(peer);
+                                if let Some(step) = self.try_emit_seller(peer) {
+                                    return step;
+                                }
+                            }
+                            RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                                tracing::debug!(%peer, "Inbound failure when requesting spot price: {:#}", error);
+                                self.spot_price_done.insert(peer);
+                                if let Some(step) = self.try_emit_seller(peer) {
+                                    return step;
+                                }
                             }
+                            RequestResponseEvent::ResponseSent { .. } => unreachable!()
                         }
-                        _ => {}
                     }
-                }
-            }
-
-            match self.state {
-                State::WaitForDiscovery => {
-                    continue;
-                }
-                State::WaitForQuoteCompletion => {
-                    let all_quotes_fetched = self
-                        .asb_quote_status
-                        .iter()
-                        .map(|(peer_id, quote_status)| match quote_status {
-                            QuoteStatus::Pending => Err(StillPending {}),
-                            QuoteStatus::Received(quote) => {
-                                let address = self
-                                    .asb_address
-                                    .get(&peer_id)
-                                    .expect("if we got a quote we must have stored an address");
-
-                                Ok(Seller {
-                                    peer_id: *peer_id,
-                                    multiaddr: address.clone(),
-                                    quote: *quote,
-                                })
+                    SwarmEvent::Behaviour(OutEvent::Ping(PingEvent { peer, result })) => {
+                        // Once a seller's quote is in and we have an RTT for it we no longer need
+                        // to rank it, so leave that RTT as-is instead of letting later pings churn it.
+                        let quote_received = matches!(self.asb_quote_status.get(&peer), Some(QuoteStatus::Received(_)));
+                        if !quote_received || !self.latencies.contains_key(&peer) {
+                            if let Ok(PingSuccess::Ping { rtt }) = result {
+                                self.latencies.insert(peer, rtt);
                             }
-                        })
-                        .collect::<Result<Vec<_>, _>>();
+                        }
 
-                    match all_quotes_fetched {
-                        Ok(sellers) => break sellers,
-                        Err(StillPending {}) => continue,
+                        self.ping_done.insert(peer);
+                        if let Some(step) = self.try_emit_seller(peer) {
+                            return step;
+                        }
                     }
+                    _ => {}
                 }
             }
+            _ = self.rediscover_interval.tick() => {
+                if !matches!(self.state, State::WaitForDiscovery) {
+                    self.discover();
+                }
+            }
+            _ = &mut self.deadline => {
+                tracing::info!("Discovery deadline reached");
+                self.draining = true;
+            }
+        }
+
+        if self.draining {
+            return self.drain_unemitted().unwrap_or(Step::Done);
+        }
+
+        if let Some(peer) = self.next_stale_peer() {
+            tracing::debug!(%peer, "Seller did not answer quote request within {:?}", QUOTE_TIMEOUT);
+            if let Some(step) = self.quote_failure(peer, FailureAction::Retry) {
+                return step;
+            }
+        }
+
+        if self.stop_when_idle && self.is_idle() {
+            tracing::info!("All known sellers have been quoted or given up on, stopping early");
+            return Step::Done;
+        }
+
+        Step::Continue
+    }
+
+    /// Whether discovery has happened at least once and every seller we know
+    /// about has either been emitted as `Online` or given up on. Gated on
+    /// `state` so we don't stop before the first `Discovered` event just
+    /// because no peer is `Pending` yet (i.e. before we have discovered
+    /// anyone at all). A `Received` quote that hasn't been emitted yet (still
+    /// waiting on a spot price or a first ping) is not idle, so we don't stop
+    /// early and strand it un-correlated.
+    fn is_idle(&self) -> bool {
+        matches!(self.state, State::WaitForQuoteCompletion)
+            && self.asb_quote_status.iter().all(|(peer, status)| match status {
+                QuoteStatus::Pending { .. } => false,
+                QuoteStatus::Received(_) => self.emitted.contains(peer),
+            })
+    }
+
+    fn discover(&mut self) {
+        self.swarm.behaviour_mut().rendezvous.discover(
+            Some(Namespace::new(self.namespace.to_string()).expect("our namespace to be a correct string")),
+            self.cookie.clone(),
+            None,
+            self.rendezvous_peer_id,
+        );
+    }
+
+    /// A peer that has been `Pending` for longer than [`QUOTE_TIMEOUT`], if
+    /// any, so a seller that connected but never answered the quote request
+    /// does not wedge discovery until the overall deadline. Treated as a
+    /// [`FailureAction::Retry`] by [`Self::quote_failure`], same as a
+    /// request-response-level timeout, so the retry-once behaviour applies
+    /// here too instead of [`QUOTE_TIMEOUT`] (10s) always winning the race
+    /// against the much longer request-response timeout.
+    fn next_stale_peer(&self) -> Option<PeerId> {
+        self.asb_quote_status.iter().find_map(|(peer, status)| match status {
+            QuoteStatus::Pending { since } if since.elapsed() > QUOTE_TIMEOUT => Some(*peer),
+            _ => None,
+        })
+    }
+
+    /// Reacts to a classified quote-protocol failure for `peer`: retries a
+    /// timeout once, otherwise removes the seller and reports why. Returns
+    /// `None` if the peer was already given up on (e.g. a duplicate event).
+    fn quote_failure(&mut self, peer: PeerId, action: FailureAction) -> Option<Step> {
+        if !self.asb_quote_status.contains_key(&peer) {
+            return None;
+        }
+
+        match action {
+            FailureAction::Retry if self.retried_quote.insert(peer) => {
+                tracing::debug!(%peer, "Quote request failed, retrying once");
+                self.asb_quote_status
+                    .insert(peer, QuoteStatus::Pending { since: Instant::now() });
+                let _request_id = self.swarm.behaviour_mut().quote.send_request(&peer, ());
+                None
+            }
+            FailureAction::Retry => {
+                tracing::debug!(%peer, "Quote request timed out again after a retry, giving up on seller");
+                self.asb_quote_status.remove(&peer);
+                Some(Step::Yield(SellerStatus::Unreachable(UnreachableSeller {
+                    peer_id: peer,
+                    reason: UnreachableReason::Timeout,
+                })))
+            }
+            FailureAction::GiveUp(UnreachableReason::UnsupportedProtocolVersion) => {
+                tracing::warn!(%peer, "Seller speaks an unsupported protocol version, skipping it");
+                self.asb_quote_status.remove(&peer);
+                Some(Step::Yield(SellerStatus::Unreachable(UnreachableSeller {
+                    peer_id: peer,
+                    reason: UnreachableReason::UnsupportedProtocolVersion,
+                })))
+            }
+            FailureAction::GiveUp(reason) => {
+                tracing::debug!(%peer, "Ignoring seller, because unable to request quote");
+                self.asb_quote_status.remove(&peer);
+                Some(Step::Yield(SellerStatus::Unreachable(UnreachableSeller {
+                    peer_id: peer,
+                    reason,
+                })))
+            }
         }
     }
-}
 
-struct StillPending {}
+    /// Emits `peer` as `Online` once its quote has arrived, if we asked for a
+    /// spot price that request has reached some terminal outcome too
+    /// (received, refused, or failed), and at least one `PingEvent` has been
+    /// observed. Without this, `Online` was emitted the instant the quote
+    /// came back - typically the fastest of the three - freezing `xmr` and
+    /// `latency` as `None` even though the spot price / ping were simply
+    /// still in flight. Returns `None` without doing anything if `peer` is
+    /// not yet ready, or was already emitted.
+    fn try_emit_seller(&mut self, peer: PeerId) -> Option<Step> {
+        if self.emitted.contains(&peer) {
+            return None;
+        }
+
+        let quote = match self.asb_quote_status.get(&peer) {
+            Some(QuoteStatus::Received(quote)) => *quote,
+            _ => return None,
+        };
+
+        if self.requested_amount.is_some() && !self.spot_price_done.contains(&peer) {
+            return None;
+        }
+
+        if !self.ping_done.contains(&peer) {
+            return None;
+        }
+
+        self.emitted.insert(peer);
+        Some(Step::Yield(SellerStatus::Online(self.seller(peer, quote))))
+    }
+
+    /// Pops one seller whose quote we already have but never got to emit
+    /// (its spot price / ping was still in flight when we stopped driving
+    /// the swarm), and yields it with whatever data actually arrived instead
+    /// of dropping it on the floor. Called once `draining` is set, in place
+    /// of the normal event-driven path in [`Self::step`].
+    fn drain_unemitted(&mut self) -> Option<Step> {
+        let emitted = &self.emitted;
+        let peer = self.asb_quote_status.iter().find_map(|(peer, status)| match status {
+            QuoteStatus::Received(_) if !emitted.contains(peer) => Some(*peer),
+            _ => None,
+        })?;
+
+        let quote = match self.asb_quote_status.get(&peer) {
+            Some(QuoteStatus::Received(quote)) => *quote,
+            _ => unreachable!("just matched a Received status for this peer above"),
+        };
+
+        self.emitted.insert(peer);
+        Some(Step::Yield(SellerStatus::Online(self.seller(peer, quote))))
+    }
+
+    fn seller(&self, peer_id: PeerId, quote: BidQuote) -> Seller {
+        let multiaddr = self
+            .asb_address
+            .get(&peer_id)
+            .expect("if we got a quote we must have stored an address")
+            .clone();
+
+        Seller {
+            peer_id,
+            multiaddr,
+            quote,
+            xmr: self.asb_spot_price.get(&peer_id).copied(),
+            latency: self.latencies.get(&peer_id).copied(),
+        }
+    }
+}
 
 impl From<PingEvent> for OutEvent {
     fn from(event: PingEvent) -> Self {
         OutEvent::Ping(event)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outbound_unsupported_protocol_gives_up_as_unsupported_protocol_version() {
+        let action = classify_outbound_failure(&OutboundFailure::UnsupportedProtocols);
+
+        assert!(matches!(
+            action,
+            FailureAction::GiveUp(UnreachableReason::UnsupportedProtocolVersion)
+        ));
+    }
+
+    #[test]
+    fn outbound_connection_closed_and_dial_failure_give_up_as_connection_failed() {
+        for error in [OutboundFailure::ConnectionClosed, OutboundFailure::DialFailure] {
+            let action = classify_outbound_failure(&error);
+
+            assert!(matches!(
+                action,
+                FailureAction::GiveUp(UnreachableReason::ConnectionFailed)
+            ));
+        }
+    }
+
+    #[test]
+    fn outbound_timeout_is_retried() {
+        let action = classify_outbound_failure(&OutboundFailure::Timeout);
+
+        assert!(matches!(action, FailureAction::Retry));
+    }
+
+    #[test]
+    fn inbound_unsupported_protocol_gives_up_as_unsupported_protocol_version() {
+        let action = classify_inbound_failure(&InboundFailure::UnsupportedProtocols);
+
+        assert!(matches!(
+            action,
+            FailureAction::GiveUp(UnreachableReason::UnsupportedProtocolVersion)
+        ));
+    }
+
+    #[test]
+    fn inbound_connection_closed_and_response_omission_give_up_as_connection_failed() {
+        for error in [InboundFailure::ConnectionClosed, InboundFailure::ResponseOmission] {
+            let action = classify_inbound_failure(&error);
+
+            assert!(matches!(
+                action,
+                FailureAction::GiveUp(UnreachableReason::ConnectionFailed)
+            ));
+        }
+    }
+
+    #[test]
+    fn inbound_timeout_is_retried() {
+        let action = classify_inbound_failure(&InboundFailure::Timeout);
+
+        assert!(matches!(action, FailureAction::Retry));
+    }
+}
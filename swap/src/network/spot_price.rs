@@ -0,0 +1,216 @@
+use crate::network::request_response::{CborCodec, BUF_SIZE, TIMEOUT};
+use libp2p::core::ProtocolName;
+use libp2p::request_response::{
+    ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+    RequestResponseMessage,
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Ask a maker what exact amount of XMR it commits to sending for a given
+/// amount of BTC, as opposed to [`crate::network::quote`] which only
+/// advertises a price and min/max bounds and leaves the conversion to the
+/// caller.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpotPriceProtocol;
+
+impl ProtocolName for SpotPriceProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        b"/comit/xmr/btc/spot-price/1.0.0"
+    }
+}
+
+pub type OutEvent = RequestResponseEvent<SpotPriceRequest, SpotPriceResponse>;
+pub type Message = RequestResponseMessage<SpotPriceRequest, SpotPriceResponse>;
+
+pub type Behaviour = RequestResponse<CborCodec<SpotPriceProtocol, SpotPriceRequest, SpotPriceResponse>>;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SpotPriceRequest {
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    pub btc: bitcoin::Amount,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum SpotPriceResponse {
+    Xmr(monero::Amount),
+    Error(Error),
+}
+
+/// Why a maker refused to quote an exact amount.
+#[derive(thiserror::Error, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Error {
+    #[error("Refusing to quote: requested amount {buy} is below our minimum {min}")]
+    AmountBelowMinimum {
+        #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+        min: bitcoin::Amount,
+        #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+        buy: bitcoin::Amount,
+    },
+    #[error("Refusing to quote: requested amount {buy} is above our maximum {max}")]
+    AmountAboveMaximum {
+        #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+        max: bitcoin::Amount,
+        #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+        buy: bitcoin::Amount,
+    },
+    #[error("Refusing to quote: our blockchain network does not match the caller's")]
+    BlockchainNetworkMismatch {
+        cli: bitcoin::Network,
+        asb: bitcoin::Network,
+    },
+}
+
+/// Builds a [`Behaviour`] suitable for the CLI, which only ever sends
+/// requests and never needs to serve them.
+pub fn cli() -> Behaviour {
+    Behaviour::new(
+        CborCodec::new(BUF_SIZE),
+        vec![(SpotPriceProtocol, ProtocolSupport::Outbound)],
+        config(),
+    )
+}
+
+/// Builds a [`Behaviour`] suitable for the ASB, which only ever serves
+/// requests and never needs to send them.
+///
+/// Wiring an inbound [`SpotPriceRequest`] to an actual [`SpotPriceResponse`]
+/// is the ASB event loop's job (it owns the min/max bounds and the current
+/// XMR rate); this snapshot only contains the CLI event loop, so that wiring
+/// is a follow-up. [`quote`] is the pure piece of that logic the ASB-side
+/// handler will call once it exists.
+pub fn asb() -> Behaviour {
+    Behaviour::new(
+        CborCodec::new(BUF_SIZE),
+        vec![(SpotPriceProtocol, ProtocolSupport::Inbound)],
+        config(),
+    )
+}
+
+/// Turns a spot-price request into a response, given the maker's current
+/// quoting parameters. Separated from the ASB event loop so it can be unit
+/// tested and reused without dragging in the swarm.
+pub fn quote(
+    request: SpotPriceRequest,
+    min: bitcoin::Amount,
+    max: bitcoin::Amount,
+    our_network: bitcoin::Network,
+    their_network: bitcoin::Network,
+    xmr_for_requested_btc: monero::Amount,
+) -> SpotPriceResponse {
+    if our_network != their_network {
+        return SpotPriceResponse::Error(Error::BlockchainNetworkMismatch {
+            cli: their_network,
+            asb: our_network,
+        });
+    }
+
+    if request.btc < min {
+        return SpotPriceResponse::Error(Error::AmountBelowMinimum {
+            min,
+            buy: request.btc,
+        });
+    }
+
+    if request.btc > max {
+        return SpotPriceResponse::Error(Error::AmountAboveMaximum {
+            max,
+            buy: request.btc,
+        });
+    }
+
+    SpotPriceResponse::Xmr(xmr_for_requested_btc)
+}
+
+fn config() -> RequestResponseConfig {
+    let mut config = RequestResponseConfig::default();
+    config.set_request_timeout(Duration::from_secs(TIMEOUT));
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_rejects_amount_below_minimum() {
+        let request = SpotPriceRequest {
+            btc: bitcoin::Amount::from_sat(100),
+        };
+
+        let response = quote(
+            request,
+            bitcoin::Amount::from_sat(200),
+            bitcoin::Amount::from_sat(1_000),
+            bitcoin::Network::Bitcoin,
+            bitcoin::Network::Bitcoin,
+            monero::Amount::from_pico(1),
+        );
+
+        assert!(matches!(
+            response,
+            SpotPriceResponse::Error(Error::AmountBelowMinimum { .. })
+        ));
+    }
+
+    #[test]
+    fn quote_rejects_amount_above_maximum() {
+        let request = SpotPriceRequest {
+            btc: bitcoin::Amount::from_sat(2_000),
+        };
+
+        let response = quote(
+            request,
+            bitcoin::Amount::from_sat(200),
+            bitcoin::Amount::from_sat(1_000),
+            bitcoin::Network::Bitcoin,
+            bitcoin::Network::Bitcoin,
+            monero::Amount::from_pico(1),
+        );
+
+        assert!(matches!(
+            response,
+            SpotPriceResponse::Error(Error::AmountAboveMaximum { .. })
+        ));
+    }
+
+    #[test]
+    fn quote_rejects_network_mismatch_before_checking_bounds() {
+        let request = SpotPriceRequest {
+            btc: bitcoin::Amount::from_sat(100),
+        };
+
+        let response = quote(
+            request,
+            bitcoin::Amount::from_sat(200),
+            bitcoin::Amount::from_sat(1_000),
+            bitcoin::Network::Bitcoin,
+            bitcoin::Network::Testnet,
+            monero::Amount::from_pico(1),
+        );
+
+        assert!(matches!(
+            response,
+            SpotPriceResponse::Error(Error::BlockchainNetworkMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn quote_accepts_amount_within_bounds() {
+        let request = SpotPriceRequest {
+            btc: bitcoin::Amount::from_sat(500),
+        };
+        let xmr = monero::Amount::from_pico(123);
+
+        let response = quote(
+            request,
+            bitcoin::Amount::from_sat(200),
+            bitcoin::Amount::from_sat(1_000),
+            bitcoin::Network::Bitcoin,
+            bitcoin::Network::Bitcoin,
+            xmr,
+        );
+
+        assert!(matches!(response, SpotPriceResponse::Xmr(amount) if amount == xmr));
+    }
+}